@@ -0,0 +1,138 @@
+const fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut seed = 0x2545_F491_4F6C_DD1D_u64;
+    let mut i = 0;
+    while i < 256 {
+        seed = splitmix64(seed);
+        table[i] = seed;
+        i += 1;
+    }
+    table
+}
+
+static GEAR: [u64; 256] = gear_table();
+
+pub struct CdcParams {
+    pub min: u64,
+    pub avg: u64,
+    pub max: u64,
+    mask_small: u64,
+    mask_large: u64,
+}
+
+impl CdcParams {
+    pub fn new(min: u64, avg: u64, max: u64) -> Self {
+        let bits = (avg.max(1) as f64).log2().round() as u32;
+        // Normalized chunking (FastCDC): a stricter (more one-bits) mask is
+        // used below the average size to discourage premature cuts, and a
+        // looser one above it to encourage the boundary to land soon.
+        let mask_small = (1u64 << (bits + 1).min(63)) - 1;
+        let mask_large = (1u64 << bits.saturating_sub(1)) - 1;
+
+        CdcParams { min, avg, max, mask_small, mask_large }
+    }
+}
+
+pub struct CdcState {
+    hash: u64,
+    chunk_len: u64,
+}
+
+impl CdcState {
+    pub fn new() -> Self {
+        CdcState { hash: 0, chunk_len: 0 }
+    }
+
+    pub fn reset(&mut self) {
+        self.hash = 0;
+        self.chunk_len = 0;
+    }
+
+    pub fn chunk_len(&self) -> u64 {
+        self.chunk_len
+    }
+
+    pub fn push(&mut self, byte: u8, params: &CdcParams) -> bool {
+        self.chunk_len += 1;
+        self.hash = (self.hash << 1).wrapping_add(GEAR[byte as usize]);
+
+        if self.chunk_len >= params.max {
+            return true;
+        }
+
+        if self.chunk_len < params.min {
+            return false;
+        }
+
+        if self.chunk_len < params.avg {
+            self.hash & params.mask_small == 0
+        } else {
+            self.hash & params.mask_large == 0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_bytes(len: usize) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(len);
+        let mut seed = 0x1234_5678_9abc_def0_u64;
+        for _ in 0..len {
+            seed = splitmix64(seed);
+            bytes.push((seed >> 56) as u8);
+        }
+        bytes
+    }
+
+    fn chunk_lengths(bytes: &[u8], params: &CdcParams) -> Vec<u64> {
+        let mut state = CdcState::new();
+        let mut lengths = Vec::new();
+
+        for &byte in bytes {
+            if state.push(byte, params) {
+                lengths.push(state.chunk_len());
+                state.reset();
+            }
+        }
+
+        if state.chunk_len() > 0 {
+            lengths.push(state.chunk_len());
+        }
+
+        lengths
+    }
+
+    #[test]
+    fn chunk_lengths_stay_within_min_and_max() {
+        let params = CdcParams::new(64, 256, 1024);
+        let bytes = sample_bytes(64 * 1024);
+        let lengths = chunk_lengths(&bytes, &params);
+
+        assert!(lengths.len() > 1);
+
+        let last = lengths.len() - 1;
+        for (i, &len) in lengths.iter().enumerate() {
+            assert!(len <= params.max, "chunk {} length {} exceeds max {}", i, len, params.max);
+            if i != last {
+                assert!(len >= params.min, "chunk {} length {} is below min {}", i, len, params.min);
+            }
+        }
+    }
+
+    #[test]
+    fn chunk_boundaries_are_deterministic() {
+        let params = CdcParams::new(64, 256, 1024);
+        let bytes = sample_bytes(64 * 1024);
+
+        assert_eq!(chunk_lengths(&bytes, &params), chunk_lengths(&bytes, &params));
+    }
+}
@@ -0,0 +1,81 @@
+use memchr::memchr;
+use memchr::memchr_iter;
+
+pub fn find_newline(a: &[u8], b: &[u8]) -> Option<usize> {
+    memchr(b'\n', a).map(|p| p + 1)
+        .or_else(|| memchr(b'\n', b).map(|p| a.len() + p + 1))
+}
+
+pub enum LineLimit {
+    Lines(u64),
+    Bytes(u64),
+}
+
+pub struct LineState {
+    lines_seen: u64,
+    bytes_seen: u64,
+}
+
+impl LineState {
+    pub fn new() -> Self {
+        LineState { lines_seen: 0, bytes_seen: 0 }
+    }
+
+    pub fn reset(&mut self) {
+        self.lines_seen = 0;
+        self.bytes_seen = 0;
+    }
+
+    // Returns how many of the bytes in `a ++ b` belong to the current
+    // chunk, or `None` if the boundary isn't in the staged bytes yet.
+    pub fn find_cut(&mut self, a: &[u8], b: &[u8], limit: &LineLimit) -> Option<usize> {
+        match limit {
+            LineLimit::Lines(n) => self.find_line_cut(a, b, *n),
+            LineLimit::Bytes(size) => self.find_byte_cut(a, b, *size),
+        }
+    }
+
+    fn find_line_cut(&mut self, a: &[u8], b: &[u8], n: u64) -> Option<usize> {
+        for idx in memchr_iter(b'\n', a) {
+            self.lines_seen += 1;
+            if self.lines_seen == n {
+                return Some(idx + 1);
+            }
+        }
+        for idx in memchr_iter(b'\n', b) {
+            self.lines_seen += 1;
+            if self.lines_seen == n {
+                return Some(a.len() + idx + 1);
+            }
+        }
+        self.bytes_seen += (a.len() + b.len()) as u64;
+        None
+    }
+
+    fn find_byte_cut(&mut self, a: &[u8], b: &[u8], size: u64) -> Option<usize> {
+        let total_len = a.len() + b.len();
+        let threshold = size.saturating_sub(self.bytes_seen) as usize;
+
+        if threshold >= total_len {
+            self.bytes_seen += total_len as u64;
+            return None;
+        }
+
+        let start = threshold;
+        let found = if start < a.len() {
+            memchr(b'\n', &a[start..]).map(|p| start + p)
+                .or_else(|| memchr(b'\n', b).map(|p| a.len() + p))
+        } else {
+            let bstart = start - a.len();
+            memchr(b'\n', &b[bstart..]).map(|p| a.len() + bstart + p)
+        };
+
+        match found {
+            Some(p) => Some(p + 1),
+            None => {
+                self.bytes_seen += total_len as u64;
+                None
+            }
+        }
+    }
+}
@@ -1,20 +1,49 @@
+mod cdc;
+mod linesplit;
+mod manifest;
+mod merge;
+
 use clap::{Clap};
 use std::path::{Path, PathBuf};
 use std::fs;
 use ubyte::{ByteUnit, ToByteUnit};
-use num::Integer;
 use std::io::{BufReader, BufWriter, Read, Write, IoSlice, IoSliceMut};
+use std::os::unix::fs::FileExt;
 use circbuf::CircBuf;
+use cdc::{CdcParams, CdcState};
+use linesplit::{find_newline, LineLimit, LineState};
+use manifest::{ChunkEntry, Digester, HashAlgorithm, Manifest};
+use merge::parse_chunk_suffix;
 
 static DEFAULT_CHUNK_SIZE: u64 = 104_857_600;
+static DEFAULT_CDC_MIN: u64 = 1_048_576;
+static DEFAULT_CDC_AVG: u64 = 4_194_304;
+static DEFAULT_CDC_MAX: u64 = 16_777_216;
 type Error = Box<dyn std::error::Error + 'static>;
 
 fn parse_ubyte(src: &str) -> std::result::Result<ByteUnit, Error> {
     return Ok(src.parse().map_err(|e| format!("error parsing byte quantity: {}", e))?)
 }
 
+fn parse_round_robin(src: &str) -> std::result::Result<u64, Error> {
+    let n = src.strip_prefix("r/").ok_or_else(|| format!("expected round-robin spec like r/N, got: {}", src))?;
+    let n: u64 = n.parse().map_err(|e| format!("error parsing round-robin count: {}", e))?;
+
+    if n == 0 {
+        return Err(format!("round-robin count must be at least 1, got: {}", src).into());
+    }
+
+    Ok(n)
+}
+
+#[derive(Clap, Debug)]
+enum Opts {
+    Split(SplitOpts),
+    Merge(MergeOpts),
+}
+
 #[derive(Clap, Debug)]
-struct Opts {
+struct SplitOpts {
     #[clap(short, long)]
     pub file: PathBuf,
 
@@ -26,62 +55,579 @@ struct Opts {
 
     #[clap(short, long)]
     pub dest: Option<PathBuf>,
+
+    #[clap(long)]
+    pub cdc: bool,
+
+    #[clap(long, parse(try_from_str = parse_ubyte))]
+    pub min: Option<ByteUnit>,
+
+    #[clap(long, parse(try_from_str = parse_ubyte))]
+    pub avg: Option<ByteUnit>,
+
+    #[clap(long, parse(try_from_str = parse_ubyte))]
+    pub max: Option<ByteUnit>,
+
+    #[clap(long)]
+    pub lines: Option<u64>,
+
+    #[clap(long, parse(try_from_str = parse_ubyte))]
+    pub line_bytes: Option<ByteUnit>,
+
+    #[clap(short, long)]
+    pub jobs: Option<u64>,
+
+    #[clap(long, parse(try_from_str = parse_round_robin))]
+    pub number: Option<u64>,
+
+    // Only supported for fixed-size splitting; ignored (with a warning)
+    // for --cdc, --lines/--line-bytes and --number.
+    #[clap(long, parse(try_from_str = HashAlgorithm::parse))]
+    pub hash: Option<HashAlgorithm>,
+}
+
+#[derive(Clap, Debug)]
+struct MergeOpts {
+    #[clap(short, long)]
+    pub file: PathBuf,
+
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+
+    #[clap(short, long, parse(try_from_str = parse_ubyte))]
+    pub expected_size: Option<ByteUnit>,
+
+    #[clap(short, long)]
+    pub manifest: Option<PathBuf>,
 }
 
 fn main() {
     let opts: Opts = Opts::parse();
     println!("opts: {:?}", opts);
 
+    match opts {
+        Opts::Split(opts) => main_split(opts),
+        Opts::Merge(opts) => main_merge(opts),
+    }
+}
+
+fn main_split(opts: SplitOpts) {
     let dest = opts.dest.unwrap_or(PathBuf::from("."));
     if !dest.is_dir() {
         fs::create_dir_all(&dest).expect("create dest dir");
     }
 
+    if opts.cdc {
+        if opts.hash.is_some() {
+            println!("--hash is not supported with --cdc yet, splitting without a manifest");
+        }
+
+        let min = opts.min.unwrap_or(DEFAULT_CDC_MIN.bytes()).as_u64();
+        let avg = opts.avg.unwrap_or(DEFAULT_CDC_AVG.bytes()).as_u64();
+        let max = opts.max.unwrap_or(DEFAULT_CDC_MAX.bytes()).as_u64();
+
+        split_cdc(&opts.file, &dest, &CdcParams::new(min, avg, max)).expect("split");
+        return;
+    }
+
+    if let Some(n) = opts.lines {
+        if opts.hash.is_some() {
+            println!("--hash is not supported with --lines yet, splitting without a manifest");
+        }
+
+        split_lines(&opts.file, &dest, LineLimit::Lines(n)).expect("split");
+        return;
+    }
+
+    if let Some(size) = opts.line_bytes {
+        if opts.hash.is_some() {
+            println!("--hash is not supported with --line-bytes yet, splitting without a manifest");
+        }
+
+        split_lines(&opts.file, &dest, LineLimit::Bytes(size.as_u64())).expect("split");
+        return;
+    }
+
+    if let Some(n) = opts.number {
+        if opts.hash.is_some() {
+            println!("--hash is not supported with --number yet, splitting without a manifest");
+        }
+
+        split_round_robin(&opts.file, &dest, n).expect("split");
+        return;
+    }
+
     let metadata = fs::metadata(&opts.file).expect("stat file");
     let file_len = metadata.len();
     let mut chunk_size = opts.size.unwrap_or(DEFAULT_CHUNK_SIZE.bytes());
 
     if let Some(chunks) = opts.chunks {
-        chunk_size = file_len.div_ceil(&chunks).bytes();
+        chunk_size = file_len.div_ceil(chunks).bytes();
     }
 
-    let chunks = opts.chunks.unwrap_or(file_len.div_ceil(&chunk_size.as_u64()));
+    let chunks = opts.chunks.unwrap_or(file_len.div_ceil(chunk_size.as_u64()));
 
-    split(&opts.file, &dest, chunk_size.as_u64(), chunks).expect("split");
+    match opts.jobs {
+        Some(jobs) if jobs > 1 && opts.hash.is_none() && metadata.is_file() => {
+            split_parallel(&opts.file, &dest, chunk_size.as_u64(), chunks, file_len, jobs).expect("split");
+        }
+        Some(jobs) if jobs > 1 && opts.hash.is_some() => {
+            println!("--hash is not supported with --jobs yet, falling back to sequential split");
+            split(&opts.file, &dest, chunk_size.as_u64(), chunks, file_len, opts.hash).expect("split");
+        }
+        Some(jobs) if jobs > 1 => {
+            println!("input is not a seekable regular file, falling back to sequential split");
+            split(&opts.file, &dest, chunk_size.as_u64(), chunks, file_len, opts.hash).expect("split");
+        }
+        _ => {
+            split(&opts.file, &dest, chunk_size.as_u64(), chunks, file_len, opts.hash).expect("split");
+        }
+    }
 }
 
-fn split(file: &Path, dest: &Path, size: u64, chunks: u64) -> std::result::Result<(), Error> {
+fn main_merge(opts: MergeOpts) {
+    let expected_size = opts.expected_size.map(|s| s.as_u64());
+    merge(&opts.file, opts.output.as_deref(), expected_size, opts.manifest.as_deref()).expect("merge");
+}
+
+fn split(file: &Path, dest: &Path, size: u64, chunks: u64, file_len: u64, hash: Option<HashAlgorithm>) -> std::result::Result<(), Error> {
     let file_name = file.file_name().ok_or_else(|| format!("no file name"))?;
+    let file_name_str = file_name.to_str().ok_or_else(|| format!("file name is not valid UTF-8"))?.to_owned();
     let width = (chunks as f64).log10().trunc() as usize + 1;
 
     let file_handle = fs::File::open(file).map_err(|e| format!("error opening input file: {:?}", e))?;
     let mut buf_reader = BufReader::new(file_handle);
     let mut buffer = CircBuf::with_capacity(1.megabytes().as_u64() as usize)?;
-    
+    let mut chunk_entries = Vec::new();
+
     for i in 0..chunks {
+        let mut chunk_file_name = file_name.to_owned();
+        chunk_file_name.push(format!(".{:01$}", i + 1, width));
+        let chunk_file_path = dest.join(&chunk_file_name);
+        println!("copying chunk {}", i);
+
+        let digester = hash.map(|h| h.digester());
+        let (next_reader, written, digester) = create_chunk(buf_reader, &chunk_file_path, size, &mut buffer, digester)?;
+        buf_reader = next_reader;
+
+        if let Some(digester) = digester {
+            chunk_entries.push(ChunkEntry {
+                name: chunk_file_name.to_string_lossy().into_owned(),
+                size: written,
+                digest: digester.finish_hex(),
+            });
+        }
+    }
+
+    if let Some(hash) = hash {
+        let manifest = Manifest {
+            file_name: file_name_str.clone(),
+            total_size: file_len,
+            mode: format!("fixed size={}", size),
+            hash_algorithm: hash,
+            chunks: chunk_entries,
+        };
+
+        manifest.write(&dest.join(format!("{}.manifest", file_name_str)))?;
+    }
+
+    Ok(())
+}
+
+fn split_parallel(file: &Path, dest: &Path, size: u64, chunks: u64, file_len: u64, jobs: u64) -> std::result::Result<(), Error> {
+    let file_name = file.file_name().ok_or_else(|| format!("no file name"))?;
+    let width = (chunks as f64).log10().trunc() as usize + 1;
+
+    std::thread::scope(|scope| -> std::result::Result<(), Error> {
+        let mut handles = Vec::with_capacity(jobs as usize);
+
+        for worker in 0..jobs.min(chunks.max(1)) {
+            // Errors cross the thread boundary as `String` (our `Error` is a
+            // `Box<dyn Error>`, which isn't `Send`), then get converted back
+            // once joined on the main thread below.
+            handles.push(scope.spawn(move || -> std::result::Result<(), String> {
+                split_parallel_chunk_range(file, dest, file_name, width, size, chunks, file_len, worker, jobs)
+                    .map_err(|e| e.to_string())
+            }));
+        }
+
+        for handle in handles {
+            handle.join().map_err(|_| format!("split worker thread panicked"))??;
+        }
+
+        Ok(())
+    })
+}
+
+fn split_parallel_chunk_range(
+    file: &Path,
+    dest: &Path,
+    file_name: &std::ffi::OsStr,
+    width: usize,
+    size: u64,
+    chunks: u64,
+    file_len: u64,
+    worker: u64,
+    jobs: u64,
+) -> std::result::Result<(), Error> {
+    let file_handle = fs::File::open(file).map_err(|e| format!("error opening input file: {:?}", e))?;
+
+    let mut i = worker;
+    while i < chunks {
         let mut chunk_file_name = file_name.to_owned();
         chunk_file_name.push(format!(".{:01$}", i + 1, width));
         let chunk_file_path = dest.join(chunk_file_name);
+        println!("copying chunk {} (worker {})", i, worker);
+
+        let chunk_file = fs::File::create(&chunk_file_path).map_err(|e| format!("error opening chunk file: {:?}", e))?;
+        let mut writer = BufWriter::new(chunk_file);
+
+        let offset = i * size;
+        let remaining = size.min(file_len.saturating_sub(offset));
+        copy_bytes_positional(&file_handle, &mut writer, offset, remaining)?;
+
+        i += jobs;
+    }
+
+    Ok(())
+}
+
+fn copy_bytes_positional<W: Write>(file: &fs::File, writer: &mut BufWriter<W>, mut offset: u64, mut len: u64) -> std::result::Result<(), Error> {
+    let mut buf = vec![0u8; 1.megabytes().as_u64() as usize];
+
+    while len > 0 {
+        let want = (buf.len() as u64).min(len) as usize;
+        let count = file.read_at(&mut buf[..want], offset)?;
+
+        if count == 0 {
+            break;
+        }
+
+        writer.write_all(&buf[..count])?;
+        offset += count as u64;
+        len -= count as u64;
+    }
+
+    Ok(())
+}
+
+fn merge(file: &Path, output: Option<&Path>, expected_size: Option<u64>, manifest_path: Option<&Path>) -> std::result::Result<(), Error> {
+    let dir = file.parent().unwrap_or_else(|| Path::new("."));
+    let file_name = file.file_name().ok_or_else(|| format!("no file name"))?
+        .to_str().ok_or_else(|| format!("chunk file name is not valid UTF-8"))?;
+    let (stem, start, width) = parse_chunk_suffix(file_name)?;
+
+    if start != 1 {
+        let first_chunk_suffix = format!(".{:01$}", 1, width);
+        return Err(format!("{} is not the first chunk (sequence number {}); merge from the {}{} chunk instead", file_name, start, stem, first_chunk_suffix).into());
+    }
+
+    let output = match output {
+        Some(output) => output.to_owned(),
+        None => dir.join(&stem),
+    };
+
+    let manifest = match manifest_path {
+        Some(path) => Some(Manifest::read(path)?),
+        None => {
+            let auto_path = dir.join(format!("{}.manifest", stem));
+            if auto_path.is_file() { Some(Manifest::read(&auto_path)?) } else { None }
+        }
+    };
+
+    let output_file = fs::File::create(&output).map_err(|e| format!("error opening output file: {:?}", e))?;
+    let mut writer = BufWriter::new(output_file);
+    let mut buffer = CircBuf::with_capacity(1.megabytes().as_u64() as usize)?;
+
+    let mut total = 0u64;
+    let mut chunk_count = 0u64;
+    let mut missing_chunk_name = None;
+    let mut i = start;
+    loop {
+        let mut chunk_file_name = stem.clone();
+        chunk_file_name.push_str(&format!(".{:01$}", i, width));
+        let chunk_file_path = dir.join(&chunk_file_name);
+
+        if !chunk_file_path.is_file() {
+            missing_chunk_name = Some(chunk_file_name);
+            break;
+        }
+
+        println!("merging chunk {}", i);
+        let chunk_file = fs::File::open(&chunk_file_path).map_err(|e| format!("error opening chunk file: {:?}", e))?;
+        let mut reader = BufReader::new(chunk_file);
+
+        let mut digester = manifest.as_ref().map(|m| m.hash_algorithm.digester());
+        let written = copy_bytes(&mut reader, &mut writer, &mut buffer, digester_as_mut(&mut digester))?;
+
+        if let Some(manifest) = &manifest {
+            let entry = manifest.chunks.iter().find(|c| c.name == chunk_file_name)
+                .ok_or_else(|| format!("chunk {} is not listed in the manifest", chunk_file_name))?;
+
+            if written != entry.size {
+                return Err(format!("chunk {} is corrupt: expected {} bytes, got {}", chunk_file_name, entry.size, written).into());
+            }
+
+            if let Some(digester) = digester {
+                let actual = digester.finish_hex();
+                if actual != entry.digest {
+                    return Err(format!("chunk {} is corrupt: digest mismatch", chunk_file_name).into());
+                }
+            }
+        }
+
+        total += written;
+        chunk_count += 1;
+        i += 1;
+    }
+
+    if let Some(manifest) = &manifest {
+        if chunk_count != manifest.chunks.len() as u64 {
+            let missing = missing_chunk_name.as_deref().unwrap_or("<unknown>");
+            return Err(format!(
+                "missing chunk: manifest lists {} chunks, found {}; first missing part is {}",
+                manifest.chunks.len(), chunk_count, missing
+            ).into());
+        }
+
+        if total != manifest.total_size {
+            return Err(format!("merged size {} does not match manifest size {}", total, manifest.total_size).into());
+        }
+    }
+
+    if let Some(expected_size) = expected_size {
+        if total != expected_size {
+            return Err(format!("merged size {} does not match expected size {}", total, expected_size).into());
+        }
+    }
+
+    Ok(())
+}
+
+// Boundaries for content-defined and line-aware modes aren't known until the
+// content is scanned, so unlike `split` there's no upfront chunk count to
+// size the numeric suffix from; use a fixed width generous enough for any
+// realistic chunk count.
+static DYNAMIC_SUFFIX_WIDTH: usize = 6;
+
+fn split_cdc(file: &Path, dest: &Path, params: &CdcParams) -> std::result::Result<(), Error> {
+    let file_name = file.file_name().ok_or_else(|| format!("no file name"))?;
+
+    let file_handle = fs::File::open(file).map_err(|e| format!("error opening input file: {:?}", e))?;
+    let mut reader = BufReader::new(file_handle);
+    let mut buffer = CircBuf::with_capacity(1.megabytes().as_u64() as usize)?;
+    let mut state = CdcState::new();
+
+    let mut i = 0u64;
+    loop {
+        let mut chunk_file_name = file_name.to_owned();
+        chunk_file_name.push(format!(".{:01$}", i + 1, DYNAMIC_SUFFIX_WIDTH));
+        let chunk_file_path = dest.join(chunk_file_name);
+        println!("copying cdc chunk {}", i);
+
+        let chunk_file = fs::File::create(&chunk_file_path).map_err(|e| format!("error opening chunk file: {:?}", e))?;
+        let mut writer = BufWriter::new(chunk_file);
+
+        let found_boundary = copy_bytes_cdc(&mut reader, &mut writer, &mut buffer, &mut state, params)?;
+        state.reset();
+        i += 1;
+
+        if !found_boundary {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn copy_bytes_cdc<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut BufWriter<W>,
+    buffer: &mut CircBuf,
+    state: &mut CdcState,
+    params: &CdcParams,
+) -> std::result::Result<bool, Error> {
+    loop {
+        if !buffer.is_full() {
+            let count = reader.read_v(buffer.get_avail())?;
+            buffer.advance_write(count);
+
+            if count == 0 && buffer.is_empty() {
+                return Ok(false);
+            }
+        }
+
+        let [a, b] = buffer.get_bytes();
+        let mut cut_at = None;
+
+        for (offset, &byte) in a.iter().chain(b.iter()).enumerate() {
+            if state.push(byte, params) {
+                cut_at = Some(offset + 1);
+                break;
+            }
+        }
+
+        match cut_at {
+            Some(n) => {
+                write_exact_v(writer, buffer, n)?;
+                return Ok(true);
+            }
+            None => {
+                let available = a.len() + b.len();
+                write_exact_v(writer, buffer, available)?;
+            }
+        }
+    }
+}
+
+fn write_exact_v<W: Write>(writer: &mut BufWriter<W>, buffer: &mut CircBuf, mut n: usize) -> std::result::Result<(), Error> {
+    while n > 0 {
+        let [a, b] = buffer.get_bytes();
+        let a_len = a.len().min(n);
+        let b_len = (n - a_len).min(b.len());
+        let count = writer.write_vectored(&[IoSlice::new(&a[..a_len]), IoSlice::new(&b[..b_len])])?;
+        buffer.advance_read(count);
+        n -= count;
+    }
+    Ok(())
+}
+
+fn split_round_robin(file: &Path, dest: &Path, n: u64) -> std::result::Result<(), Error> {
+    let file_name = file.file_name().ok_or_else(|| format!("no file name"))?;
+    let width = (n as f64).log10().trunc() as usize + 1;
+
+    let file_handle = fs::File::open(file).map_err(|e| format!("error opening input file: {:?}", e))?;
+    let mut reader = BufReader::new(file_handle);
+    let mut buffer = CircBuf::with_capacity(1.megabytes().as_u64() as usize)?;
+    let mut writers: Vec<Option<BufWriter<fs::File>>> = (0..n).map(|_| None).collect();
+    let mut current = 0u64;
+
+    loop {
+        if !buffer.is_full() {
+            let count = reader.read_v(buffer.get_avail())?;
+            buffer.advance_write(count);
+
+            if count == 0 && buffer.is_empty() {
+                break;
+            }
+        }
+
+        let [a, b] = buffer.get_bytes();
+        let cut = find_newline(a, b);
+        let take = cut.unwrap_or(a.len() + b.len());
+
+        if take == 0 {
+            continue;
+        }
+
+        let writer = match &mut writers[current as usize] {
+            Some(writer) => writer,
+            slot @ None => {
+                let mut chunk_file_name = file_name.to_owned();
+                chunk_file_name.push(format!(".{:01$}", current + 1, width));
+                let chunk_file_path = dest.join(chunk_file_name);
+                let chunk_file = fs::File::create(&chunk_file_path).map_err(|e| format!("error opening chunk file: {:?}", e))?;
+                slot.insert(BufWriter::new(chunk_file))
+            }
+        };
+
+        write_exact_v(writer, &mut buffer, take)?;
+
+        if cut.is_some() {
+            current = (current + 1) % n;
+        }
+    }
+
+    Ok(())
+}
+
+fn split_lines(file: &Path, dest: &Path, limit: LineLimit) -> std::result::Result<(), Error> {
+    let file_name = file.file_name().ok_or_else(|| format!("no file name"))?;
+
+    let file_handle = fs::File::open(file).map_err(|e| format!("error opening input file: {:?}", e))?;
+    let mut reader = BufReader::new(file_handle);
+    let mut buffer = CircBuf::with_capacity(1.megabytes().as_u64() as usize)?;
+    let mut state = LineState::new();
+
+    let mut i = 0u64;
+    loop {
+        let mut chunk_file_name = file_name.to_owned();
+        chunk_file_name.push(format!(".{:01$}", i + 1, DYNAMIC_SUFFIX_WIDTH));
+        let chunk_file_path = dest.join(chunk_file_name);
         println!("copying chunk {}", i);
-        buf_reader = create_chunk(buf_reader, &chunk_file_path, size, &mut buffer)?;
+
+        let chunk_file = fs::File::create(&chunk_file_path).map_err(|e| format!("error opening chunk file: {:?}", e))?;
+        let mut writer = BufWriter::new(chunk_file);
+
+        let found_boundary = copy_bytes_lines(&mut reader, &mut writer, &mut buffer, &mut state, &limit)?;
+        state.reset();
+        i += 1;
+
+        if !found_boundary {
+            break;
+        }
     }
 
     Ok(())
 }
 
-fn create_chunk<R: Read>(reader: R, chunk_file_path: &Path, size: u64, buffer: &mut CircBuf) -> std::result::Result<R, Error> {
+fn copy_bytes_lines<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut BufWriter<W>,
+    buffer: &mut CircBuf,
+    state: &mut LineState,
+    limit: &LineLimit,
+) -> std::result::Result<bool, Error> {
+    loop {
+        if !buffer.is_full() {
+            let count = reader.read_v(buffer.get_avail())?;
+            buffer.advance_write(count);
+
+            if count == 0 && buffer.is_empty() {
+                return Ok(false);
+            }
+        }
+
+        let [a, b] = buffer.get_bytes();
+
+        match state.find_cut(a, b, limit) {
+            Some(n) => {
+                write_exact_v(writer, buffer, n)?;
+                return Ok(true);
+            }
+            None => {
+                write_exact_v(writer, buffer, a.len() + b.len())?;
+            }
+        }
+    }
+}
+
+// Option<Box<dyn Digester>>::as_deref_mut() pins the borrow to 'static (the
+// implicit lifetime of `dyn Digester` in DerefMut::Target), which conflicts
+// with moving the Option again afterward. Box::as_mut() doesn't have that
+// problem, so reborrow through a plain match instead.
+fn digester_as_mut(digest: &mut Option<Box<dyn Digester>>) -> Option<&mut dyn Digester> {
+    match digest {
+        Some(digest) => Some(digest.as_mut()),
+        None => None,
+    }
+}
+
+fn create_chunk<R: Read>(reader: R, chunk_file_path: &Path, size: u64, buffer: &mut CircBuf, mut digest: Option<Box<dyn Digester>>) -> std::result::Result<(R, u64, Option<Box<dyn Digester>>), Error> {
     let chunk_file = fs::File::create(chunk_file_path).map_err(|e| format!("error opening chunk file: {:?}", e))?;
 
     let mut writer = BufWriter::new(chunk_file);
 
     let mut chunk_reader = reader.take(size);
 
-    copy_bytes(&mut chunk_reader, &mut writer, buffer)?;
+    let written = copy_bytes(&mut chunk_reader, &mut writer, buffer, digester_as_mut(&mut digest))?;
 
-    Ok(chunk_reader.into_inner())
+    Ok((chunk_reader.into_inner(), written, digest))
 }
 
-fn copy_bytes<R: Read,W: Write>(reader: &mut R, writer: &mut BufWriter<W>, buffer: &mut CircBuf) -> std::result::Result<(), Error> {
+fn copy_bytes<R: Read, W: Write>(reader: &mut R, writer: &mut BufWriter<W>, buffer: &mut CircBuf, mut digest: Option<&mut dyn Digester>) -> std::result::Result<u64, Error> {
+    let mut total = 0u64;
+
     loop {
         if !buffer.is_full() {
             let count = reader.read_v(buffer.get_avail())?;
@@ -91,19 +637,33 @@ fn copy_bytes<R: Read,W: Write>(reader: &mut R, writer: &mut BufWriter<W>, buffe
                 break;
             }
         }
-        
+
         if !buffer.is_empty() {
-            let count = writer.write_v(buffer.get_bytes())?;
-            buffer.advance_read(count);
+            total += write_and_digest(writer, buffer, &mut digest)? as u64;
         }
     }
 
     while !buffer.is_empty() {
-        let count = writer.write_v(buffer.get_bytes())?;
-        buffer.advance_read(count);
+        total += write_and_digest(writer, buffer, &mut digest)? as u64;
     }
 
-    Ok(())
+    Ok(total)
+}
+
+fn write_and_digest<W: Write>(writer: &mut BufWriter<W>, buffer: &mut CircBuf, digest: &mut Option<&mut dyn Digester>) -> std::result::Result<usize, Error> {
+    let [a, b] = buffer.get_bytes();
+    let count = writer.write_v([a, b])?;
+
+    if let Some(digest) = digest {
+        let a_len = a.len().min(count);
+        let b_len = count - a_len;
+        digest.update(&a[..a_len]);
+        digest.update(&b[..b_len]);
+    }
+
+    buffer.advance_read(count);
+
+    Ok(count)
 }
 
 trait ReadVectored<R: Read> {
@@ -129,4 +689,83 @@ impl <W: Write> WriteVectored<W> for W {
         let second = IoSlice::new(bytes[1]);
         self.write_vectored(&[first, second])
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static TEST_DIR_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    fn unique_test_dir(name: &str) -> PathBuf {
+        let n = TEST_DIR_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!("filesplit-test-{}-{}-{}", std::process::id(), name, n));
+        fs::create_dir_all(&dir).expect("create test dir");
+        dir
+    }
+
+    #[test]
+    fn split_then_merge_round_trip() {
+        let dir = unique_test_dir("round-trip");
+        let input_path = dir.join("input");
+        let content = b"the quick brown fox jumps over the lazy dog".repeat(100);
+        fs::write(&input_path, &content).expect("write input");
+
+        let chunk_size = 512u64;
+        let chunks = (content.len() as u64).div_ceil(chunk_size);
+        split(&input_path, &dir, chunk_size, chunks, content.len() as u64, None).expect("split");
+
+        let first_chunk = dir.join("input.1");
+        merge(&first_chunk, None, Some(content.len() as u64), None).expect("merge");
+
+        let merged = fs::read(dir.join("input")).expect("read merged output");
+        assert_eq!(merged, content);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn merge_rejects_non_first_chunk() {
+        let dir = unique_test_dir("non-first-chunk");
+        let input_path = dir.join("input");
+        let content = b"abcdefghij".repeat(50);
+        fs::write(&input_path, &content).expect("write input");
+
+        let chunk_size = 64u64;
+        let chunks = (content.len() as u64).div_ceil(chunk_size);
+        split(&input_path, &dir, chunk_size, chunks, content.len() as u64, None).expect("split");
+
+        let middle_chunk = dir.join("input.3");
+        let result = merge(&middle_chunk, None, None, None);
+        assert!(result.is_err());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn parse_round_robin_rejects_zero() {
+        assert!(parse_round_robin("r/0").is_err());
+    }
+
+    #[test]
+    fn parse_round_robin_accepts_positive_count() {
+        assert_eq!(parse_round_robin("r/4").unwrap(), 4);
+    }
+
+    #[test]
+    fn split_round_robin_distributes_lines_in_order() {
+        let dir = unique_test_dir("round-robin");
+        let input_path = dir.join("input");
+        let content = b"line1\nline2\nline3\nline4\nline5\nline6\n";
+        fs::write(&input_path, content).expect("write input");
+
+        split_round_robin(&input_path, &dir, 3).expect("split");
+
+        assert_eq!(fs::read_to_string(dir.join("input.1")).unwrap(), "line1\nline4\n");
+        assert_eq!(fs::read_to_string(dir.join("input.2")).unwrap(), "line2\nline5\n");
+        assert_eq!(fs::read_to_string(dir.join("input.3")).unwrap(), "line3\nline6\n");
+
+        fs::remove_dir_all(&dir).ok();
+    }
 }
\ No newline at end of file
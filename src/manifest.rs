@@ -0,0 +1,134 @@
+use std::fs;
+use std::path::Path;
+
+type Error = Box<dyn std::error::Error + 'static>;
+
+#[derive(Debug, Clone, Copy)]
+pub enum HashAlgorithm {
+    Crc32,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub fn parse(src: &str) -> std::result::Result<Self, Error> {
+        match src {
+            "crc32" => Ok(HashAlgorithm::Crc32),
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            other => Err(format!("unknown hash algorithm '{}' (expected crc32 or blake3)", other).into()),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Crc32 => "crc32",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+
+    pub fn digester(&self) -> Box<dyn Digester> {
+        match self {
+            HashAlgorithm::Crc32 => Box::new(Crc32Digester(crc32fast::Hasher::new())),
+            HashAlgorithm::Blake3 => Box::new(Blake3Digester(blake3::Hasher::new())),
+        }
+    }
+}
+
+pub trait Digester {
+    fn update(&mut self, bytes: &[u8]);
+    fn finish_hex(self: Box<Self>) -> String;
+}
+
+struct Crc32Digester(crc32fast::Hasher);
+
+impl Digester for Crc32Digester {
+    fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finish_hex(self: Box<Self>) -> String {
+        format!("{:08x}", self.0.finalize())
+    }
+}
+
+struct Blake3Digester(blake3::Hasher);
+
+impl Digester for Blake3Digester {
+    fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finish_hex(self: Box<Self>) -> String {
+        self.0.finalize().to_hex().to_string()
+    }
+}
+
+pub struct ChunkEntry {
+    pub name: String,
+    pub size: u64,
+    pub digest: String,
+}
+
+pub struct Manifest {
+    pub file_name: String,
+    pub total_size: u64,
+    pub mode: String,
+    pub hash_algorithm: HashAlgorithm,
+    pub chunks: Vec<ChunkEntry>,
+}
+
+impl Manifest {
+    pub fn write(&self, path: &Path) -> std::result::Result<(), Error> {
+        let mut contents = String::new();
+        contents.push_str(&format!("file: {}\n", self.file_name));
+        contents.push_str(&format!("size: {}\n", self.total_size));
+        contents.push_str(&format!("mode: {}\n", self.mode));
+        contents.push_str(&format!("hash: {}\n", self.hash_algorithm.name()));
+
+        for chunk in &self.chunks {
+            contents.push_str(&format!("chunk: {} {} {}\n", chunk.name, chunk.size, chunk.digest));
+        }
+
+        fs::write(path, contents).map_err(|e| format!("error writing manifest: {:?}", e))?;
+
+        Ok(())
+    }
+
+    pub fn read(path: &Path) -> std::result::Result<Manifest, Error> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("error reading manifest: {:?}", e))?;
+
+        let mut file_name = None;
+        let mut total_size = None;
+        let mut mode = None;
+        let mut hash_algorithm = None;
+        let mut chunks = Vec::new();
+
+        for line in contents.lines() {
+            let (key, rest) = line.split_once(':').ok_or_else(|| format!("malformed manifest line: {}", line))?;
+            let rest = rest.trim();
+
+            match key {
+                "file" => file_name = Some(rest.to_owned()),
+                "size" => total_size = Some(rest.parse::<u64>().map_err(|e| format!("error parsing manifest size: {}", e))?),
+                "mode" => mode = Some(rest.to_owned()),
+                "hash" => hash_algorithm = Some(HashAlgorithm::parse(rest)?),
+                "chunk" => {
+                    let mut parts = rest.splitn(3, ' ');
+                    let name = parts.next().ok_or_else(|| format!("malformed chunk entry: {}", rest))?;
+                    let size = parts.next().ok_or_else(|| format!("malformed chunk entry: {}", rest))?
+                        .parse::<u64>().map_err(|e| format!("error parsing chunk size: {}", e))?;
+                    let digest = parts.next().ok_or_else(|| format!("malformed chunk entry: {}", rest))?;
+                    chunks.push(ChunkEntry { name: name.to_owned(), size, digest: digest.to_owned() });
+                }
+                other => return Err(format!("unknown manifest field: {}", other).into()),
+            }
+        }
+
+        Ok(Manifest {
+            file_name: file_name.ok_or_else(|| format!("manifest missing 'file'"))?,
+            total_size: total_size.ok_or_else(|| format!("manifest missing 'size'"))?,
+            mode: mode.ok_or_else(|| format!("manifest missing 'mode'"))?,
+            hash_algorithm: hash_algorithm.ok_or_else(|| format!("manifest missing 'hash'"))?,
+            chunks,
+        })
+    }
+}
@@ -0,0 +1,13 @@
+pub fn parse_chunk_suffix(file_name: &str) -> Result<(String, u64, usize), String> {
+    let dot = file_name.rfind('.').ok_or_else(|| format!("chunk file name has no numeric suffix: {}", file_name))?;
+    let (stem, rest) = file_name.split_at(dot);
+    let digits = &rest[1..];
+
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(format!("chunk file name has no numeric suffix: {}", file_name));
+    }
+
+    let start = digits.parse::<u64>().map_err(|e| format!("error parsing chunk suffix: {}", e))?;
+
+    Ok((stem.to_owned(), start, digits.len()))
+}